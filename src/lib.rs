@@ -14,10 +14,13 @@ pub mod error;
 
 pub use error::*;
 
+use std::collections::{BTreeMap, HashMap};
 use std::convert::From;
 use std::env::{self, home_dir, split_paths};
 use std::ffi::OsString;
 use std::fs;
+use std::io::{BufRead, BufReader};
+use std::iter;
 use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
@@ -107,6 +110,40 @@ pub fn get_cache_home() -> Result<PathBuf> {
     get_cache_home_from_env(&env::var_os)
 }
 
+/// Get the state home directory given a closure that returns the the value of an environment variable.
+/// This method allows having a custom environment.
+///
+/// If ```$XDG_STATE_HOME``` is not set, it returns ```$HOME/.local/state```.
+pub fn get_state_home_from_env<F>(get_env_var: &F) -> Result<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    get_env_path_or_default(get_env_var, "XDG_STATE_HOME", ".local/state")
+}
+
+/// Get the state home directory.
+///
+/// If ```$XDG_STATE_HOME``` is not set, it returns ```$HOME/.local/state```.
+pub fn get_state_home() -> Result<PathBuf> {
+    get_state_home_from_env(&env::var_os)
+}
+
+/// Get the user-local executable directory given a closure that returns the the value of an
+/// environment variable. This method allows having a custom environment.
+///
+/// If ```$XDG_BIN_HOME``` is not set, it returns ```$HOME/.local/bin```.
+pub fn get_bin_home_from_env<F>(get_env_var: &F) -> Result<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    get_env_path_or_default(get_env_var, "XDG_BIN_HOME", ".local/bin")
+}
+
+/// Get the user-local executable directory.
+///
+/// If ```$XDG_BIN_HOME``` is not set, it returns ```$HOME/.local/bin```.
+pub fn get_bin_home() -> Result<PathBuf> {
+    get_bin_home_from_env(&env::var_os)
+}
+
 /// Get $XDG_RUNTIME_DIR if found in the environment.
 /// This method allows having a custom environment.
 ///
@@ -126,6 +163,241 @@ pub fn get_runtime_dir() -> Option<PathBuf> {
     get_runtime_dir_from_env(&env::var_os)
 }
 
+/// A resolved, application-scoped view of the XDG base directories.
+///
+/// Where the free functions in this crate return bare directories, ```BaseDirectories```
+/// captures them once for a given application prefix and offers higher-level helpers for
+/// placing and finding files within them.
+pub struct BaseDirectories {
+    prefix: PathBuf,
+    data_home: PathBuf,
+    config_home: PathBuf,
+    cache_home: PathBuf,
+    runtime_dir: Option<PathBuf>,
+    data_dirs: Vec<PathBuf>,
+    config_dirs: Vec<PathBuf>,
+}
+
+impl BaseDirectories {
+    /// Create a new ```BaseDirectories``` with no application prefix.
+    pub fn new() -> Result<BaseDirectories> {
+        BaseDirectories::with_prefix("")
+    }
+
+    /// Create a new ```BaseDirectories```, placing and finding files under the given
+    /// application prefix.
+    pub fn with_prefix<P: AsRef<Path>>(prefix: P) -> Result<BaseDirectories> {
+        BaseDirectories::with_prefix_from_env(prefix, &env::var_os)
+    }
+
+    /// Create a new ```BaseDirectories``` given a closure that returns the the value of an
+    /// environment variable. This method allows having a custom environment.
+    pub fn with_prefix_from_env<P, F>(prefix: P, get_env_var: &F) -> Result<BaseDirectories>
+        where P: AsRef<Path>, F: Fn(&str) -> Option<OsString>
+    {
+        Ok(BaseDirectories {
+            prefix: prefix.as_ref().to_path_buf(),
+            data_home: try!(get_data_home_from_env(get_env_var)),
+            config_home: try!(get_config_home_from_env(get_env_var)),
+            cache_home: try!(get_cache_home_from_env(get_env_var)),
+            runtime_dir: get_runtime_dir_from_env(get_env_var),
+            data_dirs: get_data_dirs_from_env(get_env_var),
+            config_dirs: get_config_dirs_from_env(get_env_var),
+        })
+    }
+
+    /// Get $XDG_RUNTIME_DIR for this application, if found in the environment.
+    pub fn runtime_dir(&self) -> Option<&Path> {
+        self.runtime_dir.as_ref().map(|p| p.as_path())
+    }
+
+    /// Given a path relative to the config home, create its parent directories and return
+    /// the full path to write a config file at.
+    pub fn place_config_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        place_path(&self.config_home, &self.prefix, path)
+    }
+
+    /// Given a path relative to the data home, create its parent directories and return
+    /// the full path to write a data file at.
+    pub fn place_data_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        place_path(&self.data_home, &self.prefix, path)
+    }
+
+    /// Given a path relative to the cache home, create its parent directories and return
+    /// the full path to write a cache file at.
+    pub fn place_cache_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        place_path(&self.cache_home, &self.prefix, path)
+    }
+
+    /// Search the config home, then each ```$XDG_CONFIG_DIRS``` entry in order, for ```path```
+    /// under the application prefix, returning the first one found.
+    pub fn find_config_file<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        find_path(&self.config_home, &self.config_dirs, &self.prefix, path)
+    }
+
+    /// Search the data home, then each ```$XDG_DATA_DIRS``` entry in order, for ```path```
+    /// under the application prefix, returning the first one found.
+    pub fn find_data_file<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        find_path(&self.data_home, &self.data_dirs, &self.prefix, path)
+    }
+
+    /// List every file found under ```<config_home>/<prefix>/path``` and the matching
+    /// directory of each ```$XDG_CONFIG_DIRS``` entry, merged by relative path with the
+    /// config home taking precedence over system directories.
+    ///
+    /// Each result pairs the path relative to ```<dir>/<prefix>/path``` with the absolute
+    /// source path that was chosen for it, ordered by relative path.
+    pub fn list_config_files<P: AsRef<Path>>(&self, path: P) -> Vec<(PathBuf, PathBuf)> {
+        list_files(&self.config_home, &self.config_dirs, &self.prefix, path)
+    }
+
+    /// List every file found under ```<data_home>/<prefix>/path``` and the matching
+    /// directory of each ```$XDG_DATA_DIRS``` entry, merged by relative path with the
+    /// data home taking precedence over system directories.
+    ///
+    /// Each result pairs the path relative to ```<dir>/<prefix>/path``` with the absolute
+    /// source path that was chosen for it, ordered by relative path.
+    pub fn list_data_files<P: AsRef<Path>>(&self, path: P) -> Vec<(PathBuf, PathBuf)> {
+        list_files(&self.data_home, &self.data_dirs, &self.prefix, path)
+    }
+}
+
+/// Join ```home``` with ```prefix``` and ```path```, create the parent directories and
+/// return the resulting path.
+fn place_path<P: AsRef<Path>>(home: &Path, prefix: &Path, path: P) -> Result<PathBuf> {
+    let full_path = home.join(prefix).join(path);
+    if let Some(parent) = full_path.parent() {
+        try!(fs::create_dir_all(parent));
+    }
+    Ok(full_path)
+}
+
+/// Search ```home``` and then each of ```dirs```, in order, for ```prefix```/```path```,
+/// returning the first that exists as a file.
+fn find_path<P: AsRef<Path>>(home: &Path, dirs: &[PathBuf], prefix: &Path, path: P) -> Option<PathBuf> {
+    let path = path.as_ref();
+    iter::once(home)
+        .chain(dirs.iter().map(|p| p.as_path()))
+        .map(|dir| dir.join(prefix).join(path))
+        .find(|full_path| full_path.is_file())
+}
+
+/// Walk ```home``` and then each of ```dirs```, in order, collecting every file found under
+/// ```<dir>/<prefix>/path```, keyed by its path relative to that directory so that a file
+/// found in an earlier (higher-priority) directory shadows one of the same relative path
+/// found later. Results are returned in a stable order, sorted by relative path.
+fn list_files<P: AsRef<Path>>(home: &Path, dirs: &[PathBuf], prefix: &Path, path: P) -> Vec<(PathBuf, PathBuf)> {
+    let path = path.as_ref();
+    let mut found = BTreeMap::new();
+
+    for dir in iter::once(home).chain(dirs.iter().map(|p| p.as_path())) {
+        let base = dir.join(prefix).join(path);
+        collect_files(&base, &PathBuf::new(), &mut found);
+    }
+
+    found.into_iter().collect()
+}
+
+/// Recursively collect the files under ```dir```, inserting ```relative -> absolute``` pairs
+/// into ```found``` for any relative path not already present.
+fn collect_files(dir: &Path, relative: &Path, found: &mut BTreeMap<PathBuf, PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let absolute = entry.path();
+        let relative = relative.join(entry.file_name());
+
+        if absolute.is_dir() {
+            collect_files(&absolute, &relative, found);
+        } else if !found.contains_key(&relative) {
+            found.insert(relative, absolute);
+        }
+    }
+}
+
+/// The well-known keys that may appear in ```user-dirs.dirs```, as defined by the
+/// xdg-user-dirs mechanism.
+const USER_DIR_KEYS: &'static [&'static str] = &[
+    "XDG_DESKTOP_DIR",
+    "XDG_DOWNLOAD_DIR",
+    "XDG_DOCUMENTS_DIR",
+    "XDG_MUSIC_DIR",
+    "XDG_PICTURES_DIR",
+    "XDG_VIDEOS_DIR",
+    "XDG_PUBLICSHARE_DIR",
+    "XDG_TEMPLATES_DIR",
+];
+
+/// Parse ```<config_home>/user-dirs.dirs``` and return the well-known user directories
+/// (```XDG_DESKTOP_DIR```, ```XDG_DOWNLOAD_DIR```, ...) it defines.
+///
+/// If the config home cannot be resolved, or ```user-dirs.dirs``` does not exist or
+/// cannot be parsed, an empty map is returned rather than an error, since the
+/// xdg-user-dirs mechanism is optional.
+pub fn get_user_dirs() -> HashMap<String, PathBuf> {
+    get_user_dirs_from_env(&env::var_os)
+}
+
+/// Parse ```<config_home>/user-dirs.dirs``` given a closure that returns the the value
+/// of an environment variable. This method allows having a custom environment.
+pub fn get_user_dirs_from_env<F>(get_env_var: &F) -> HashMap<String, PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    let mut dirs = HashMap::new();
+
+    let config_home = match get_config_home_from_env(get_env_var) {
+        Ok(path) => path,
+        Err(_) => return dirs,
+    };
+    let home = match home_dir_from_env(get_env_var) {
+        Some(path) => path,
+        None => return dirs,
+    };
+    let file = match fs::File::open(config_home.join("user-dirs.dirs")) {
+        Ok(file) => file,
+        Err(_) => return dirs,
+    };
+
+    for line in BufReader::new(file).lines().filter_map(|l| l.ok()) {
+        parse_user_dir_line(&line, &home, &mut dirs);
+    }
+
+    dirs
+}
+
+/// Parse a single line of a ```user-dirs.dirs``` file, inserting the result into ```dirs```
+/// if the line sets one of the recognised keys.
+fn parse_user_dir_line(line: &str, home: &Path, dirs: &mut HashMap<String, PathBuf>) {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return;
+    }
+
+    let eq = match line.find('=') {
+        Some(eq) => eq,
+        None => return,
+    };
+
+    let key = line[..eq].trim();
+    if !USER_DIR_KEYS.contains(&key) {
+        return;
+    }
+
+    let value = line[eq + 1..].trim().trim_matches('"');
+    let path = if value == "$HOME" {
+        home.to_path_buf()
+    } else if value.starts_with("$HOME/") {
+        home.join(&value["$HOME/".len()..])
+    } else {
+        PathBuf::from(value)
+    };
+
+    dirs.insert(key.to_string(), path);
+}
+
 /// Check that the value set for ```$XDG_RUNTIME_DIR``` is a valid path, has the correct owner and
 /// permissions.
 ///
@@ -168,10 +440,42 @@ fn get_env_path_or_default<F>(get_env_var: &F, env_var: &str, default: &str) ->
     where F: Fn(&str) -> Option<OsString>
 {
     get_env_path(get_env_var, env_var)
-        .or(home_dir().map(|p| p.join(default)))
+        .or(home_dir_from_env(get_env_var).map(|p| p.join(default)))
         .ok_or(From::from(XdgError::NoHomeDir))
 }
 
+/// Resolve the user's home directory on Unix, honoring ```$HOME``` from the given closure
+/// (so a custom environment can override it in tests) before falling back to
+/// ```std::env::home_dir```, which is reliable on Unix.
+#[cfg(unix)]
+fn home_dir_from_env<F>(get_env_var: &F) -> Option<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    get_env_path(get_env_var, "HOME").or_else(home_dir)
+}
+
+/// Resolve the user's home directory on Windows, honoring ```$HOME``` from the given
+/// closure, then ```std::env::home_dir``` (which can return ```None``` or an unusable
+/// value), then falling back to ```%LOCALAPPDATA%``` before giving up.
+#[cfg(windows)]
+fn home_dir_from_env<F>(get_env_var: &F) -> Option<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    get_env_path(get_env_var, "HOME")
+        .or_else(home_dir)
+        .or_else(|| get_env_path(get_env_var, "LOCALAPPDATA"))
+}
+
+/// Resolve the user's home directory on platforms that are neither Unix nor Windows,
+/// honoring ```$HOME``` from the given closure before falling back to
+/// ```std::env::home_dir```.
+#[cfg(not(any(unix, windows)))]
+fn home_dir_from_env<F>(get_env_var: &F) -> Option<PathBuf>
+    where F: Fn(&str) -> Option<OsString>
+{
+    get_env_path(get_env_var, "HOME").or_else(home_dir)
+}
+
 /// Get an environment variable's value as a PathBuf.
 fn get_env_path<F>(get_env_var: &F, env_var: &str) -> Option<PathBuf>
     where F: Fn(&str) -> Option<OsString>
@@ -238,10 +542,11 @@ mod inner {
 mod tests {
     use super::*;
 
-    use std::collections::HashMap;
     use std::env::{self, home_dir, join_paths, split_paths};
     use std::ffi::OsString;
+    use std::io::Write;
     use std::path::PathBuf;
+    use std::process;
 
     #[test]
     fn test_env_with_no_xdg_vars() {
@@ -255,6 +560,8 @@ mod tests {
         assert!(get_config_home_from_env(&f).unwrap() == home_dir().unwrap().join(".config"));
         assert!(get_config_dirs_from_env(&f)          == vec![PathBuf::from("/etc/xdg")]);
         assert!(get_cache_home_from_env(&f).unwrap()  == home_dir().unwrap().join(".cache"));
+        assert!(get_state_home_from_env(&f).unwrap()  == home_dir().unwrap().join(".local/state"));
+        assert!(get_bin_home_from_env(&f).unwrap()    == home_dir().unwrap().join(".local/bin"));
         assert!(get_runtime_dir_from_env(&f)          == None);
     }
 
@@ -274,6 +581,8 @@ mod tests {
         assert!(get_config_home_from_env(&f).unwrap() == home_dir().unwrap().join(".config"));
         assert!(get_config_dirs_from_env(&f)          == vec![PathBuf::from("/etc/xdg")]);
         assert!(get_cache_home_from_env(&f).unwrap()  == home_dir().unwrap().join(".cache"));
+        assert!(get_state_home_from_env(&f).unwrap()  == home_dir().unwrap().join(".local/state"));
+        assert!(get_bin_home_from_env(&f).unwrap()    == home_dir().unwrap().join(".local/bin"));
         assert!(get_runtime_dir_from_env(&f)          == None);
     }
 
@@ -296,4 +605,93 @@ mod tests {
         assert!(get_config_dirs_from_env(&f)          == split_paths(&custom_env["XDG_CONFIG_DIRS"]).collect::<Vec<PathBuf>>());
         assert!(get_cache_home_from_env(&f).unwrap()  == custom_env.get("XDG_CACHE_HOME").map(PathBuf::from).unwrap());
     }
+
+    #[test]
+    fn test_get_user_dirs_from_env() {
+        let tmp_dir = env::temp_dir().join(format!("xdg-rs-test-user-dirs-{}", process::id()));
+        fs::create_dir_all(&tmp_dir).unwrap();
+
+        {
+            let mut file = fs::File::create(tmp_dir.join("user-dirs.dirs")).unwrap();
+            writeln!(file, "# this is a comment").unwrap();
+            writeln!(file, "").unwrap();
+            writeln!(file, "XDG_DESKTOP_DIR=\"$HOME/Desktop\"").unwrap();
+            writeln!(file, "XDG_DOWNLOAD_DIR=\"/mnt/downloads\"").unwrap();
+            writeln!(file, "XDG_UNKNOWN_DIR=\"$HOME/Unknown\"").unwrap();
+        }
+
+        let mut custom_env = HashMap::new();
+        custom_env.insert("XDG_CONFIG_HOME", tmp_dir.clone().into_os_string());
+
+        let f = |var: &str| { custom_env.get(var).map(OsString::from) };
+        let dirs = get_user_dirs_from_env(&f);
+
+        assert!(dirs.get("XDG_DESKTOP_DIR")  == Some(&home_dir().unwrap().join("Desktop")));
+        assert!(dirs.get("XDG_DOWNLOAD_DIR") == Some(&PathBuf::from("/mnt/downloads")));
+        assert!(dirs.get("XDG_UNKNOWN_DIR")  == None);
+
+        fs::remove_dir_all(&tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn test_base_directories_place_and_find_config_file() {
+        let base = env::temp_dir().join(format!("xdg-rs-test-base-dirs-{}", process::id()));
+        let home_config = base.join("home/config");
+        let system_config = base.join("system/config");
+        fs::create_dir_all(system_config.join("myapp")).unwrap();
+
+        fs::File::create(system_config.join("myapp/system.conf")).unwrap();
+
+        let mut custom_env = HashMap::new();
+        custom_env.insert("XDG_DATA_HOME", base.join("home/data").into_os_string());
+        custom_env.insert("XDG_CONFIG_HOME", home_config.clone().into_os_string());
+        custom_env.insert("XDG_CONFIG_DIRS", system_config.clone().into_os_string());
+        custom_env.insert("XDG_CACHE_HOME", base.join("home/cache").into_os_string());
+
+        let f = |var: &str| { custom_env.get(var).map(OsString::from) };
+        let dirs = BaseDirectories::with_prefix_from_env("myapp", &f).unwrap();
+
+        assert!(dirs.find_config_file("missing.conf") == None);
+        assert!(dirs.find_config_file("system.conf") == Some(system_config.join("myapp/system.conf")));
+
+        let placed = dirs.place_config_file("nested/app.conf").unwrap();
+        assert!(placed == home_config.join("myapp/nested/app.conf"));
+        assert!(placed.parent().unwrap().is_dir());
+        assert!(!placed.exists());
+        fs::File::create(&placed).unwrap();
+
+        assert!(dirs.find_config_file("nested/app.conf") == Some(placed));
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_base_directories_list_config_files_merges_with_precedence() {
+        let base = env::temp_dir().join(format!("xdg-rs-test-list-files-{}", process::id()));
+        let home_config = base.join("home/config");
+        let system_config = base.join("system/config");
+        fs::create_dir_all(home_config.join("myapp")).unwrap();
+        fs::create_dir_all(system_config.join("myapp")).unwrap();
+
+        fs::File::create(home_config.join("myapp/a.conf")).unwrap();
+        fs::File::create(system_config.join("myapp/a.conf")).unwrap();
+        fs::File::create(system_config.join("myapp/b.conf")).unwrap();
+
+        let mut custom_env = HashMap::new();
+        custom_env.insert("XDG_DATA_HOME", base.join("home/data").into_os_string());
+        custom_env.insert("XDG_CONFIG_HOME", home_config.clone().into_os_string());
+        custom_env.insert("XDG_CONFIG_DIRS", system_config.clone().into_os_string());
+        custom_env.insert("XDG_CACHE_HOME", base.join("home/cache").into_os_string());
+
+        let f = |var: &str| { custom_env.get(var).map(OsString::from) };
+        let dirs = BaseDirectories::with_prefix_from_env("myapp", &f).unwrap();
+
+        let files = dirs.list_config_files("");
+        assert!(files == vec![
+            (PathBuf::from("a.conf"), home_config.join("myapp/a.conf")),
+            (PathBuf::from("b.conf"), system_config.join("myapp/b.conf")),
+        ]);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
 }